@@ -0,0 +1,62 @@
+//! Benchmarks for [`benc::load`] / [`benc::Parser`] against a few representative payloads:
+//! a small dict, a deeply nested structure, and a large byte-string-heavy torrent-like blob.
+//! Run with `cargo bench` once a manifest wires this crate up; guards against regressions in
+//! the parser the same way the tree-building tests guard against correctness regressions.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+#[path = "../src/benc.rs"]
+mod benc;
+
+fn small_dict() -> String {
+    "d3:foo3:bar3:bazi42e3:quxli1ei2ei3eee".to_string()
+}
+
+fn deeply_nested(depth: usize) -> String {
+    let mut s = String::with_capacity(depth * 2 + 3);
+
+    for _ in 0..depth {
+        s.push('l');
+    }
+
+    s.push_str("i0e");
+
+    for _ in 0..depth {
+        s.push('e');
+    }
+
+    s
+}
+
+/// A single-file torrent's `info` dict, with a `pieces` field made of `piece_count` SHA-1
+/// hashes (20 bytes each) — the field that makes real-world torrents multi-megabyte.
+fn large_pieces(piece_count: usize) -> String {
+    let pieces: String = std::iter::repeat('\0').take(piece_count * 20).collect();
+
+    format!("d6:lengthi{}e6:pieces{}:{}ee", piece_count * 262144, pieces.len(), pieces)
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let small = small_dict();
+    let nested = deeply_nested(256);
+    let large = large_pieces(5000);
+
+    let mut group = c.benchmark_group("load");
+
+    group.bench_function("small_dict", |b| {
+        b.iter(|| benc::load_str(black_box(&small)).unwrap());
+    });
+
+    group.bench_function("deeply_nested", |b| {
+        b.iter(|| benc::load_str(black_box(&nested)).unwrap());
+    });
+
+    group.bench_function("large_pieces", |b| {
+        b.iter(|| benc::load_str(black_box(&large)).unwrap());
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -1,21 +1,454 @@
 use std::{
+    collections::VecDeque,
     fmt,
-    io::Error as IoError,
-    path::Path,
+    fs,
+    io::{self, Error as IoError, ErrorKind, IsTerminal},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::interactive::{self, CmdError, Format, State};
+
 pub enum Error {
     Io(IoError),
+    Batch(Vec<BatchFailure>),
+    Expand(String),
+}
+
+/// Knobs that apply uniformly to every file in a batch run, grouped into one struct so
+/// `batch()` and its worker don't have to thread a dozen individual parameters through.
+pub struct BatchOptions {
+    pub stop_on_error: bool,
+    pub read_only: bool,
+    pub output: Option<PathBuf>,
+    pub format: Format,
+    pub jobs: usize,
+    pub skip_invalid: bool,
+    pub skip_not_found: bool,
+    pub recursive: bool,
+    pub glob: Option<String>,
+    /// Run every script but write nothing, reporting which files would have changed.
+    pub check: bool,
+    /// `Some(true)`/`Some(false)` force progress reporting on or off; `None` auto-detects
+    /// based on whether stderr is a terminal.
+    pub progress: Option<bool>,
+}
+
+/// Filter applied when walking a directory input without an explicit `--glob`.
+const DEFAULT_GLOB: &str = "*.torrent";
+
+/// How long to park between polls of the front in-flight handle when every worker slot is
+/// busy, so waiting for one to finish doesn't spin a core.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Whether `input` contains any glob metacharacter, i.e. should be expanded against the
+/// filesystem rather than treated as a literal path.
+fn is_glob_pattern(input: &Path) -> bool {
+    input.to_str().is_some_and(|s| s.contains(['*', '?', '[', ']']))
+}
+
+/// Expand `inputs` into a flat list of files to process. Plain paths that exist on disk pass
+/// through unchanged. Directories are walked — honoring `.gitignore` and `.bencignore` files
+/// the same way `rg`/`fd` do — and narrowed to `glob_filter` (or [`DEFAULT_GLOB`] if unset);
+/// `recursive` controls whether the walk descends past the directory's direct children.
+/// Anything else is treated as a standalone glob pattern (e.g. a shell-quoted
+/// `downloads/**/*.torrent`) and expanded against the filesystem directly.
+fn expand_inputs(inputs: &[PathBuf], recursive: bool, glob_filter: Option<&str>) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+
+    for input in inputs {
+        if input.is_dir() {
+            let pattern = glob_filter.unwrap_or(DEFAULT_GLOB);
+            let overrides = OverrideBuilder::new(input)
+                .add(pattern)
+                .map_err(|e| Error::Expand(e.to_string()))?
+                .build()
+                .map_err(|e| Error::Expand(e.to_string()))?;
+
+            let mut walker = WalkBuilder::new(input);
+            walker.add_custom_ignore_filename(".bencignore");
+            walker.overrides(overrides);
+
+            if !recursive {
+                walker.max_depth(Some(1));
+            }
+
+            for entry in walker.build() {
+                let entry = entry.map_err(|e| Error::Expand(e.to_string()))?;
+
+                if entry.file_type().is_some_and(|t| t.is_file()) {
+                    files.push(entry.into_path());
+                }
+            }
+        } else if input.exists() || !is_glob_pattern(input) {
+            // Either it's a literal existing file, or it's missing but has no glob metacharacters
+            // to expand: pass it through as-is so `process_file`'s NotFound handling (and
+            // `--skip-not-found`) still sees it, instead of silently dropping a typo'd path via
+            // an empty glob match.
+            files.push(input.clone());
+        } else {
+            let pattern = input.to_str().ok_or_else(|| {
+                Error::Expand(format!("{}: not valid UTF-8", input.display()))
+            })?;
+
+            for entry in glob::glob(pattern).map_err(|e| Error::Expand(e.to_string()))? {
+                files.push(entry.map_err(|e| Error::Expand(e.to_string()))?);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// A single failed line from a script file, with enough context to locate it.
+pub struct ScriptFailure {
+    pub file: PathBuf,
+    pub line: usize,
+    pub col: usize,
+    pub error: CmdError,
+}
+
+impl fmt::Display for ScriptFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file.display(), self.line, self.col, self.error)
+    }
+}
+
+/// Everything that can go wrong while processing one input file: either the file itself
+/// couldn't be loaded, or one of its script commands failed against it.
+pub enum BatchFailure {
+    Load { file: PathBuf, error: interactive::Error },
+    Script(ScriptFailure),
+    /// In `--check` mode, a file whose scripted transforms would have changed it.
+    WouldChange(PathBuf),
+}
+
+impl fmt::Display for BatchFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Load { file, error } => write!(f, "{}: {}", file.display(), error),
+            Self::Script(s) => write!(f, "{}", s),
+            Self::WouldChange(file) => write!(f, "{}: would change", file.display()),
+        }
+    }
+}
+
+/// Owns every script source loaded for a batch run so line/column errors can reference
+/// the path they came from without re-reading the file.
+struct Loader {
+    sources: Vec<(PathBuf, String)>,
+}
+
+impl Loader {
+    fn load(paths: &[PathBuf]) -> Result<Self, Error> {
+        let mut sources = Vec::with_capacity(paths.len());
+
+        for path in paths {
+            let text = fs::read_to_string(path).map_err(Error::Io)?;
+            sources.push((path.clone(), text));
+        }
+
+        Ok(Self { sources })
+    }
+
+    fn lines(&self) -> impl Iterator<Item = (&Path, usize, &str)> {
+        self.sources.iter().flat_map(|(path, text)| {
+            text.lines().enumerate().map(move |(i, line)| (path.as_path(), i + 1, line))
+        })
+    }
+}
+
+/// Which of four mutually-exclusive buckets a file landed in, tallied into the summary line
+/// `batch` prints once every file has been processed.
+#[derive(Clone, Copy)]
+enum FileStatus {
+    Processed,
+    SkippedInvalid,
+    SkippedNotFound,
+    Failed,
+}
+
+/// The result of running every script against a single input file.
+struct FileOutcome {
+    index: usize,
+    file: PathBuf,
+    status: FileStatus,
+    failures: Vec<BatchFailure>,
+}
+
+/// Run every script against one file. Spawned onto a worker thread by [`batch`], so every
+/// argument has to be owned rather than borrowed.
+fn process_file(index: usize, file: PathBuf, loader: Arc<Loader>, options: Arc<BatchOptions>) -> FileOutcome {
+    let mut failures = Vec::new();
+
+    let mut state = match State::new(&file, options.read_only, options.output.clone(), options.format) {
+        Ok(state) => state,
+
+        Err(interactive::Error::Io(e)) if e.kind() == ErrorKind::NotFound => {
+            if options.skip_not_found {
+                return FileOutcome { index, file, status: FileStatus::SkippedNotFound, failures };
+            }
+
+            failures.push(BatchFailure::Load { file: file.clone(), error: interactive::Error::Io(e) });
+            return FileOutcome { index, file, status: FileStatus::Failed, failures };
+        }
+
+        Err(e) => {
+            if options.skip_invalid {
+                return FileOutcome { index, file, status: FileStatus::SkippedInvalid, failures };
+            }
+
+            failures.push(BatchFailure::Load { file: file.clone(), error: e });
+            return FileOutcome { index, file, status: FileStatus::Failed, failures };
+        }
+    };
+
+    for (script, line_no, line) in loader.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (cmd, argbuf) = match interactive::split_command(line) {
+            Some(parts) => parts,
+            None => continue,
+        };
+
+        let col = line.len() - argbuf.len() - if argbuf.is_empty() { 0 } else { 1 } + 1;
+
+        if let Err(e) = interactive::interactive_cmd(&mut state, cmd, argbuf) {
+            failures.push(BatchFailure::Script(ScriptFailure {
+                file: script.to_path_buf(),
+                line: line_no,
+                col,
+                error: e,
+            }));
+
+            if options.stop_on_error {
+                break;
+            }
+        }
+    }
+
+    if options.check {
+        // Don't report per-file here: workers finish in completion order, not input order, and
+        // a `would change` entry would print twice — once here, once in the final input-ordered
+        // `Error::Batch` summary that `batch()` assembles from `failures` once every file is
+        // done. Just record it; `batch()` is the single place that reports it.
+        if state.changed {
+            failures.push(BatchFailure::WouldChange(file.clone()));
+        }
+    } else if state.changed {
+        if let Err(e) = state.save() {
+            eprintln!("Error saving {}: {}", file.display(), e);
+        }
+    }
+
+    // A `--check` file that would merely change isn't a failure in the sense the summary line
+    // cares about — it was successfully loaded and scripted, just not written. Only a load or
+    // script error should count against it.
+    let status = if failures.iter().any(|f| !matches!(f, BatchFailure::WouldChange(_))) {
+        FileStatus::Failed
+    } else {
+        FileStatus::Processed
+    };
+
+    FileOutcome { index, file, status, failures }
+}
+
+/// Live feedback for a batch run. Auto-detected (or forced via `--progress`/`--no-progress`)
+/// between a live `indicatif` bar when stderr is a terminal, and periodic one-line status
+/// updates otherwise so redirected output stays readable instead of filling up with bar
+/// escape codes.
+enum Progress {
+    Bar(ProgressBar),
+    Status { total: usize, done: usize, last: Instant, interval: Duration },
+    Disabled,
+}
+
+impl Progress {
+    fn new(show: Option<bool>, total: usize) -> Self {
+        match show {
+            Some(false) => Self::Disabled,
+            Some(true) => Self::bar(total),
+            None if io::stderr().is_terminal() => Self::bar(total),
+            None => Self::Status { total, done: 0, last: Instant::now(), interval: Duration::from_secs(2) },
+        }
+    }
+
+    fn bar(total: usize) -> Self {
+        let bar = ProgressBar::new(total as u64);
+
+        if let Ok(style) = ProgressStyle::with_template("{bar:40} {pos}/{len} {msg} ({per_sec}, eta {eta})") {
+            bar.set_style(style);
+        }
+
+        Self::Bar(bar)
+    }
+
+    /// Record that `file` just finished processing.
+    fn tick(&mut self, file: &Path) {
+        match self {
+            Self::Bar(bar) => {
+                bar.set_message(file.display().to_string());
+                bar.inc(1);
+            }
+
+            Self::Status { total, done, last, interval } => {
+                *done += 1;
+
+                if last.elapsed() >= *interval || *done == *total {
+                    eprintln!("[{}/{}] {}", done, total, file.display());
+                    *last = Instant::now();
+                }
+            }
+
+            Self::Disabled => {}
+        }
+    }
+
+    fn finish(&self) {
+        if let Self::Bar(bar) = self {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+/// Run every script against every file, reporting a consolidated list of failures instead
+/// of aborting on the first one (unless `options.stop_on_error` is set, in which case the
+/// first load or script error stops the run; a `--check` would-change entry never does).
+///
+/// Up to `options.jobs` files are processed concurrently: each gets its own worker thread,
+/// kept in a `VecDeque` of in-flight handles. While there's still work queued, the front
+/// handle is polled without blocking (`JoinHandle::is_finished`) — ready, it's popped and its
+/// result recorded; not ready, it's cycled to the back and the next handle is tried after a
+/// brief [`POLL_INTERVAL`] park, so a saturated queue waiting on a slow file doesn't spin a
+/// core. Once every file has been launched, the remaining handles are drained by blocking on
+/// `join` in turn. Failures are recorded per file index and reported in input order once the
+/// whole batch is done, regardless of which order the workers actually finished in.
+///
+/// Progress is reported via `options.progress` as each file finishes (see [`Progress`]), and a
+/// final summary line — processed, skipped-invalid, skipped-not-found, failed — is always
+/// printed once the run completes.
+pub fn batch(files: Vec<PathBuf>, scripts: Vec<PathBuf>, options: BatchOptions) -> Result<(), Error> {
+    let loader = Arc::new(Loader::load(&scripts)?);
+    let jobs = options.jobs.max(1);
+    let files = expand_inputs(&files, options.recursive, options.glob.as_deref())?;
+    let mut progress = Progress::new(options.progress, files.len());
+    let options = Arc::new(options);
+
+    let mut outcomes: Vec<Option<FileOutcome>> = (0..files.len()).map(|_| None).collect();
+    let mut pending = files.into_iter().enumerate();
+    let mut in_flight: VecDeque<JoinHandle<FileOutcome>> = VecDeque::new();
+    let mut aborted = false;
+
+    while pending.len() > 0 || !in_flight.is_empty() {
+        while !aborted && in_flight.len() < jobs {
+            let (index, file) = match pending.next() {
+                Some(item) => item,
+                None => break,
+            };
+
+            let loader = Arc::clone(&loader);
+            let options = Arc::clone(&options);
+
+            in_flight.push_back(thread::spawn(move || process_file(index, file, loader, options)));
+        }
+
+        if in_flight.is_empty() {
+            break;
+        }
+
+        let outcome = if aborted || pending.len() == 0 || in_flight.front().unwrap().is_finished() {
+            in_flight.pop_front().unwrap().join().expect("worker thread panicked")
+        } else {
+            // The front handle isn't done yet and every worker slot is busy: park briefly
+            // instead of spinning the queue, then cycle it to the back and try the next handle.
+            thread::sleep(POLL_INTERVAL);
+            let handle = in_flight.pop_front().unwrap();
+            in_flight.push_back(handle);
+            continue;
+        };
+
+        progress.tick(&outcome.file);
+
+        if options.stop_on_error && matches!(outcome.status, FileStatus::Failed) {
+            aborted = true;
+        }
+
+        let index = outcome.index;
+        outcomes[index] = Some(outcome);
+    }
+
+    progress.finish();
+
+    let mut processed = 0;
+    let mut skipped_invalid = 0;
+    let mut skipped_not_found = 0;
+    let mut failed = 0;
+    let mut failures = Vec::new();
+
+    for outcome in outcomes.into_iter().flatten() {
+        match outcome.status {
+            FileStatus::Processed => processed += 1,
+            FileStatus::SkippedInvalid => skipped_invalid += 1,
+            FileStatus::SkippedNotFound => skipped_not_found += 1,
+            FileStatus::Failed => failed += 1,
+        }
+
+        failures.extend(outcome.failures);
+    }
+
+    eprintln!(
+        "{} processed, {} skipped (invalid), {} skipped (not found), {} failed",
+        processed, skipped_invalid, skipped_not_found, failed
+    );
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Batch(failures))
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::Io(e) => write!(f, "IO Error: {}", e),
+            Self::Batch(failures) => {
+                writeln!(f, "{} failure(s):", failures.len())?;
+
+                for failure in failures {
+                    writeln!(f, "  {}", failure)?;
+                }
+
+                Ok(())
+            }
+            Self::Expand(msg) => write!(f, "{}", msg),
         }
     }
 }
 
-pub fn batch<P>(files: Vec<P>) -> Result<(), Error> where P: AsRef<Path> {
-    Ok(())
+impl Error {
+    /// Whether this failure includes at least one file that couldn't be loaded at all, as
+    /// opposed to one that merely failed a script command or (in `--check` mode) would have
+    /// changed. Callers use this to pick a distinct exit code for "some input was invalid".
+    pub fn has_invalid_file(&self) -> bool {
+        match self {
+            Self::Batch(failures) => failures.iter().any(|f| matches!(f, BatchFailure::Load { .. })),
+            _ => false,
+        }
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(e: IoError) -> Self {
+        Self::Io(e)
+    }
 }
@@ -6,7 +6,7 @@ use std::{
     collections::BTreeMap,
     convert::{TryFrom, TryInto},
     fmt,
-    io::{Cursor, Error as IoError, Read, Seek, SeekFrom},
+    io::{Cursor, Error as IoError, Read},
     rc::Rc,
 };
 
@@ -18,33 +18,6 @@ enum Token {
     Colon,
 }
 
-#[derive(Debug, PartialEq)]
-enum State {
-    Root,
-    Dict,
-    Int,
-    Str,
-    DictKey,
-    DictVal,
-    StrRem,
-    DictFlush,
-    DictValStr,
-    DictValInt,
-    DictValDict,
-    DictValList,
-    ListVal,
-    ListValStr,
-    ListValInt,
-    ListValDict,
-    ListValList,
-    ListFlush,
-    RootValInt,
-    RootValStr,
-    RootValDict,
-    RootValList,
-    Done,
-}
-
 #[derive(Debug, PartialEq)]
 enum TraverseState {
     Root,
@@ -88,498 +61,284 @@ pub enum Value {
 
 pub struct ValueDisplay<'a>(&'a Value, usize);
 
-pub fn load(stream: &mut (impl Read + Seek)) -> Result<Value, Error> {
-    let file_size = stream.seek(SeekFrom::End(0))?;
-    stream.seek(SeekFrom::Start(0))?;
-
-    if file_size == 0 {
-        return Err(Error::Empty);
-    }
-
-    #[cfg(test)] eprintln!("File size: {}", file_size);
-
-    let mut file_index = 0u64;
-    let mut buf_index = 0usize;
-    let mut state = State::Root;
-    let mut next_state = Vec::new();
-    let mut buf = Vec::new();
-    let mut buf_chars = buf.iter().peekable();
-    let mut buf_str = Vec::new();
-    let mut buf_str_remainder = 0u64;
-    let mut buf_int = String::new();
-    let mut key_stack = Vec::new();
-    let mut val_stack = Vec::new();
-    let mut item_stack = Vec::new();
-    let mut dict_stack = Vec::new();
-    let mut list_stack = Vec::new();
-    let mut dict_i = -1i8;
-    let mut list_i = -1i8;
-    let root;
-
-    while file_index + (buf_index as u64) < file_size {
-        let real_index = file_index + buf_index as u64;
-
-        if real_index >= (file_index + buf.len() as u64) && real_index < file_size {
-            buf.clear();
-            stream.take(CHUNK_SIZE).read_to_end(&mut buf)?;
-            buf_chars = buf.iter().peekable();
-            file_index += buf_index as u64;
-            buf_index = 0;
-        }
-
-        #[cfg(test)] {
-            eprintln!("------------------------");
-            eprintln!("real_index: {:?}", real_index);
-            eprintln!("state: {:?}", state);
-            eprintln!("dict_i: {}", dict_i);
-            eprintln!("list_i: {}", list_i);
-            eprintln!("------------------------");
-        }
-
-        match state {
-            State::Root => {
-                let c = **buf_chars.peek().unwrap();
-                #[cfg(test)]
-                eprintln!("c = {}", c);
-
-                match c.try_into() {
-                    // Dict value
-                    Ok(Token::Dict) => {
-                        buf_chars.next();
-                        buf_index += 1;
-                        dict_stack.push(Rc::new(RefCell::new(BTreeMap::new())));
-                        key_stack.push(None);
-                        val_stack.push(None);
-                        dict_i += 1;
-
-                        state = State::DictKey;
-                        next_state.push(State::RootValDict);
-                    }
-
-                    // List value
-                    Ok(Token::List) => {
-                        buf_chars.next();
-                        buf_index += 1;
-                        list_stack.push(Rc::new(RefCell::new(Vec::new())));
-                        item_stack.push(None);
-                        list_i += 1;
-
-                        state = State::ListVal;
-                        next_state.push(State::RootValList);
-                    }
+/// One token out of [`Parser`]'s event stream. `Key` and `Bytes` borrow a scratch buffer
+/// owned by the parser, so copy their contents out before asking for the next event.
+pub enum Event<'a> {
+    BeginDict,
+    Key(&'a [u8]),
+    BeginList,
+    Int(i64),
+    Bytes(&'a [u8]),
+    End,
+}
 
-                    // Int value
-                    Ok(Token::Int) => {
-                        state = State::Int;
-                        buf_chars.next();
-                        buf_index += 1;
-                        next_state.push(State::RootValInt);
-                    }
+#[derive(Clone, Copy)]
+enum Frame {
+    /// About to read a dict key, or `e` to close the dict.
+    Dict,
+    /// Just read a dict key; about to read its value.
+    DictValue,
+    /// About to read a list item, or `e` to close the list.
+    List,
+}
 
+/// Event-driven bencode reader. Unlike [`load`], it never builds a [`Value`] tree: it yields
+/// one [`Event`] at a time, so a caller that only needs one field out of a multi-megabyte
+/// torrent's `pieces` blob doesn't have to pay for the whole tree. [`load`] is a thin wrapper
+/// that drives a `Parser` and assembles the events into a `Value`.
+pub struct Parser<R> {
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+    offset: usize,
+    scratch: Vec<u8>,
+    stack: Vec<Frame>,
+    started: bool,
+}
 
-                    // Str value
-                    Err(_) => {
-                        state = State::Str;
-                        next_state.push(State::RootValStr);
-                    }
+impl<R: Read> Parser<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            pos: 0,
+            offset: 0,
+            scratch: Vec::new(),
+            stack: Vec::new(),
+            started: false,
+        }
+    }
 
-                    // End, Colon
-                    Ok(a) => return Err(
-                        Error::Syntax(real_index as usize,
-                                      format!("Unexpected '{}' token", Into::<u8>::into(a) as char))
-                    ),
-                }
+    /// Read the next event, or `Ok(None)` once the root value has been fully consumed.
+    pub fn next_event(&mut self) -> Result<Option<Event<'_>>, Error> {
+        if self.stack.is_empty() {
+            if self.started {
+                return Ok(None);
             }
 
-            // Root int value
-            // Just increase buf_index here so the loop can be broken
-            State::RootValInt => {
-                buf_index += 1;
-            }
+            self.started = true;
 
-            // Read dict key or end the dict if it's empty
-            // Internally dict keys can be anything since BTreeMap's K type is Value, but here we only
-            // consider them to be strings.
-            // FIXME: Deny non-string tokens?
-            State::DictKey => {
-                let c = **buf_chars.peek().unwrap();
-
-                if c == Token::End.into() {
-                    buf_chars.next();
-                    buf_index += 1;
-                    state = next_state.pop().ok_or(Error::StackUnderflow)?;
-                } else {
-                    if buf_str.len() == 0 {
-                        state = State::Str;
-                        next_state.push(State::DictKey);
-                    } else {
-                        key_stack[dict_i as usize] = Some(str_or_bytes(buf_str.clone()));
-                        buf_str.clear();
-                        state = State::DictVal;
-                    }
-                }
+            if !self.fill()? {
+                return Err(Error::Empty);
             }
 
-            // Read dict value
-            State::DictVal => {
-                let c = **buf_chars.peek().ok_or(Error::Eof)?;
-
-                match c.try_into() {
-                    // Dict value
-                    Ok(Token::Dict) => {
-                        let map = Rc::new(RefCell::new(BTreeMap::new()));
-
-                        buf_chars.next();
-                        buf_index += 1;
-                        val_stack[dict_i as usize] = Some(Value::DictRef(Rc::clone(&map)));
-                        dict_stack.push(map);
-                        key_stack.push(None);
-                        val_stack.push(None);
-                        dict_i += 1;
-
-                        state = State::DictKey;
-                        next_state.push(State::DictValDict);
-                    }
-
-                    // List value
-                    Ok(Token::List) => {
-                        let vec = Rc::new(RefCell::new(Vec::new()));
-
-                        buf_chars.next();
-                        buf_index += 1;
-                        val_stack[dict_i as usize] = Some(Value::ListRef(Rc::clone(&vec)));
-                        list_stack.push(vec);
-                        item_stack.push(None);
-                        list_i += 1;
-
-                        state = State::ListVal;
-                        next_state.push(State::DictValList);
-                    }
-
-                    // Int value
-                    Ok(Token::Int) => {
-                        buf_chars.next();
-                        buf_index += 1;
-                        state = State::Int;
-                        next_state.push(State::DictValInt);
-                    }
-
-                    // String value
-                    Err(_) => {
-                        state = State::Str;
-                        next_state.push(State::DictValStr);
-                    }
+            return self.read_value().map(Some);
+        }
 
-                    // Colon, End
-                    _ => return Err(Error::Syntax(real_index as usize, format!("Unexpected '{}' token", c))),
+        match *self.stack.last().unwrap() {
+            Frame::Dict => {
+                if self.peek_byte()? == u8::from(Token::End) {
+                    self.next_byte()?;
+                    self.stack.pop();
+                    Ok(Some(Event::End))
+                } else {
+                    self.read_string()?;
+                    *self.stack.last_mut().unwrap() = Frame::DictValue;
+                    Ok(Some(Event::Key(&self.scratch)))
                 }
             }
 
-            // Process current dict value as str
-            State::DictValStr => {
-                val_stack[dict_i as usize] = Some(str_or_bytes(buf_str.clone()));
-                buf_str.clear();
-                state = State::DictFlush;
+            Frame::DictValue => {
+                *self.stack.last_mut().unwrap() = Frame::Dict;
+                self.read_value().map(Some)
             }
 
-            // Process current dict value as int
-            State::DictValInt => {
-                // Unwrap here because Int state already checks for EOF
-                let c = *buf_chars.next().unwrap();
-
-                if c != Token::End.into() {
-                    return Err(Error::Syntax(real_index as usize, "Expected 'e' token".into()));
+            Frame::List => {
+                if self.peek_byte()? == u8::from(Token::End) {
+                    self.next_byte()?;
+                    self.stack.pop();
+                    Ok(Some(Event::End))
+                } else {
+                    self.read_value().map(Some)
                 }
-
-                let val = buf_int.parse::<i64>().map_err(|_| Error::Syntax(real_index as usize, "Invalid integer".into()))?;
-                val_stack[dict_i as usize] = Some(Value::Int(val));
-                buf_int.clear();
-                buf_index += 1;
-
-                state = State::DictFlush;
             }
+        }
+    }
 
-            // Process current dict value as dict
-            State::DictValDict => {
-                let dict = dict_stack.pop().ok_or(Error::StackUnderflow)?;
+    /// Read whatever comes in "value position": a container opener, an integer, or a string.
+    fn read_value(&mut self) -> Result<Event<'_>, Error> {
+        let c = self.peek_byte()?;
 
-                val_stack[dict_i as usize] = Some(Value::DictRef(dict));
-                dict_i -= 1;
-                key_stack.pop().ok_or(Error::StackUnderflow)?;
-                val_stack.pop().ok_or(Error::StackUnderflow)?;
-                state = State::DictFlush;
+        match c.try_into() {
+            Ok(Token::Dict) => {
+                self.next_byte()?;
+                self.stack.push(Frame::Dict);
+                Ok(Event::BeginDict)
             }
 
-            // Process current dict value as list
-            State::DictValList => {
-                let list = list_stack.pop().ok_or(Error::StackUnderflow)?;
-
-                val_stack[dict_i as usize] = Some(Value::ListRef(list));
-                list_i -= 1;
-                item_stack.pop().ok_or(Error::StackUnderflow)?;
-                state = State::DictFlush;
+            Ok(Token::List) => {
+                self.next_byte()?;
+                self.stack.push(Frame::List);
+                Ok(Event::BeginList)
             }
 
-            // Insert current (key, value) pair into current dict
-            State::DictFlush => {
-                let key = key_stack[dict_i as usize].clone().unwrap();
-                let val = val_stack[dict_i as usize].clone().unwrap().unref();
-                dict_stack[dict_i as usize].borrow_mut().insert(key, val);
-
-                let c = **buf_chars.peek().ok_or(Error::Eof)?;
-
-                if c == Token::End.into() {
-                    buf_chars.next();
-                    buf_index += 1;
-                    state = next_state.pop().ok_or(Error::StackUnderflow)?;
-                } else {
-                    state = State::DictKey;
-                }
+            Ok(Token::Int) => {
+                self.next_byte()?;
+                self.read_int().map(Event::Int)
             }
 
-            // List value
-            State::ListVal => {
-                let c = **buf_chars.peek().ok_or(Error::Eof)?;
-
-                match c.try_into() {
-                    // End of list
-                    Ok(Token::End) => {
-                        buf_chars.next();
-                        buf_index += 1;
-                        state = next_state.pop().ok_or(Error::StackUnderflow)?;
-                    }
-
-                    // Dict value
-                    Ok(Token::Dict) => {
-                        let d = Rc::new(RefCell::new(BTreeMap::new()));
-
-                        item_stack[list_i as usize] = Some(Value::DictRef(Rc::clone(&d)));
-                        buf_chars.next();
-                        dict_stack.push(d);
-                        key_stack.push(None);
-                        val_stack.push(None);
-                        dict_i += 1;
-                        buf_index += 1;
-
-                        state = State::DictKey;
-                        next_state.push(State::ListValDict);
-                    }
-
-                    // List value
-                    Ok(Token::List) => {
-                        let l = Rc::new(RefCell::new(Vec::new()));
-
-                        item_stack[list_i as usize] = Some(Value::ListRef(Rc::clone(&l)));
-                        buf_chars.next();
-                        list_stack.push(l);
-                        item_stack.push(None);
-                        list_i += 1;
-                        buf_index += 1;
-
-                        next_state.push(State::ListValList);
-                    }
+            Err(_) => {
+                self.read_string()?;
+                Ok(Event::Bytes(&self.scratch))
+            }
 
-                    // Int value
-                    Ok(Token::Int) => {
-                        buf_chars.next();
-                        buf_index += 1;
-                        state = State::Int;
-                        next_state.push(State::ListValInt);
-                    }
+            Ok(_) => Err(Error::Syntax(self.offset, format!("Unexpected '{}' token", c as char))),
+        }
+    }
 
-                    // String value
-                    Err(_) => {
-                        state = State::Str;
-                        next_state.push(State::ListValStr);
-                    }
+    /// Read a `<len>:<bytes>` string into `self.scratch`, replacing whatever was there before.
+    fn read_string(&mut self) -> Result<(), Error> {
+        let mut len_buf = String::new();
 
-                    // Colon
-                    _ => return Err(Error::Syntax(real_index as usize, "Unexpected ':' token".into())),
-                }
-            }
+        loop {
+            let c = self.next_byte()?;
 
-            // Process current list value as str
-            State::ListValStr => {
-                item_stack[list_i as usize] = Some(str_or_bytes(buf_str.clone()));
-                buf_str.clear();
-                state = State::ListFlush;
+            if c == u8::from(Token::Colon) {
+                break;
             }
 
-            // Process current list value as int
-            State::ListValInt => {
-                // Unwrap here because Int state already checks for EOF
-                let c = *buf_chars.next().unwrap();
+            len_buf.push(c as char);
+        }
 
-                if c != Token::End.into() {
-                    return Err(Error::Syntax(real_index as usize, "Expected 'e' token".into()));
-                }
+        let mut remaining = len_buf.parse::<u64>()
+            .map_err(|_| Error::Syntax(self.offset, "Invalid integer".into()))?;
 
-                let val = buf_int.parse::<i64>().map_err(|_| Error::Syntax(real_index as usize, "Invalid integer".into()))?;
+        self.scratch.clear();
 
-                item_stack[list_i as usize] = Some(Value::Int(val));
-                buf_int.clear();
-                buf_index += 1;
-                state = State::ListFlush;
+        while remaining > 0 {
+            if !self.fill()? {
+                return Err(Error::Eof);
             }
 
-            // Process current list value as dict
-            State::ListValDict => {
-                let dict = dict_stack.pop().ok_or(Error::StackUnderflow)?.borrow().clone();
+            let available = (self.buf.len() - self.pos) as u64;
+            let take = available.min(remaining) as usize;
 
-                item_stack[list_i as usize] = Some(Value::Dict(dict));
-                key_stack.pop();
-                val_stack.pop();
-                dict_i -= 1;
+            self.scratch.extend_from_slice(&self.buf[self.pos..(self.pos + take)]);
+            self.pos += take;
+            self.offset += take;
+            remaining -= take as u64;
+        }
 
-                state = State::ListFlush;
-            }
+        Ok(())
+    }
 
-            // Process current list value as list
-            State::ListValList => {
-                let list = list_stack.pop().ok_or(Error::StackUnderflow)?.borrow().clone();
+    /// Read an `i<digits>e` integer, the `i` having already been consumed.
+    fn read_int(&mut self) -> Result<i64, Error> {
+        let mut buf = String::new();
 
-                item_stack[list_i as usize] = Some(Value::List(list));
-                item_stack.pop();
-                list_i -= 1;
+        loop {
+            let c = self.peek_byte()?;
 
-                state = State::ListFlush;
+            if c == u8::from(Token::End) {
+                self.next_byte()?;
+                break;
             }
 
-            // Add current list value to the current list
-            State::ListFlush => {
-                let val = item_stack[list_i as usize].clone().unwrap().unref();
-                list_stack[list_i as usize].borrow_mut().push(val);
-
-                let c = **buf_chars.peek().unwrap();
-
-                if c == Token::End.into() {
-                    buf_chars.next();
-                    buf_index += 1;
-                    state = next_state.pop().ok_or(Error::StackUnderflow)?;
-                } else {
-                    state = State::ListVal;
-                }
+            if c as char == '-' && !buf.is_empty() {
+                return Err(Error::Syntax(self.offset, "Unexpected '-'".into()));
             }
 
-            // Process string
-            State::Str => {
-                if buf_int.len() == 0 {
-                    buf_str.clear();
-                    buf_str_remainder = 0;
-                    state = State::Int;
-                    next_state.push(State::Str);
-                } else {
-                    let c = *buf_chars.next().ok_or(Error::Eof)?;
-                    #[cfg(test)] eprintln!("c = {}", c);
-
-                    if c != Token::Colon.into() {
-                        return Err(Error::Syntax(real_index as usize, "Expected ':'".into()));
-                    }
+            buf.push(c as char);
+            self.next_byte()?;
+        }
 
-                    let buf_str_size = buf_int.parse::<u64>().map_err(|_| Error::Syntax(real_index as usize, "Invalid integer".into()))?;
-                    buf_int.clear();
-                    buf_index += 1;
-
-                    // String is bigger than buffer
-                    if buf_index + buf_str_size as usize > buf.len() {
-                        let chunk_size = buf.len() - buf_index;
-                        buf_str_remainder = buf_str_size - chunk_size as u64;
-                        buf_str.extend(buf_chars.by_ref());
-                        buf_index += chunk_size;
-                        state = State::StrRem;
-                    } else {
-                        buf_str.extend(buf_chars.by_ref().take(buf_str_size as usize));
-                        buf_index += buf_str_size as usize;
-                        state = next_state.pop().ok_or(Error::StackUnderflow)?;
-                    }
-                }
-            }
+        if buf.is_empty() {
+            return Err(Error::Syntax(self.offset, "Empty integer".into()));
+        }
 
-            // Process string remainder
-            State::StrRem => {
-                if buf_str_remainder > 0 && buf_index + buf_str_remainder as usize > buf.len() {
-                    let chunk_size = buf.len() - buf_index;
-                    buf_str_remainder -= chunk_size as u64;
-                    buf_str.extend(buf_chars.by_ref());
-                    buf_index += chunk_size;
-                } else {
-                    buf_str.extend(buf_chars.by_ref().take(buf_str_remainder as usize));
-                    buf_index += buf_str_remainder as usize;
-                    buf_str_remainder = 0;
-                    state = next_state.pop().ok_or(Error::StackUnderflow)?;
-                }
-            }
+        if buf.len() > MAX_INT_BUF {
+            return Err(Error::BigInt);
+        }
 
-            // Int
-            State::Int => {
-                const CHARS: &[char] = &['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '-'];
+        buf.parse::<i64>().map_err(|_| Error::Syntax(self.offset, "Invalid integer".into()))
+    }
 
-                let c = **buf_chars.peek().ok_or(Error::Eof)? as char;
-                #[cfg(test)] eprintln!("(int) c = {}", c);
+    /// Make sure at least one unread byte is available in `self.buf`, refilling from the
+    /// reader in `CHUNK_SIZE` chunks if needed. Returns `false` on EOF.
+    fn fill(&mut self) -> Result<bool, Error> {
+        if self.pos < self.buf.len() {
+            return Ok(true);
+        }
 
-                if CHARS.contains(&c) {
-                    // Only allow minus at the beginning
-                    if c == '-' && buf_int.len() > 0 {
-                        return Err(Error::Syntax(real_index as usize, "Unexpected '-'".into()));
-                    }
+        let mut chunk = vec![0u8; CHUNK_SIZE as usize];
+        let n = self.reader.read(&mut chunk)?;
 
-                    buf_int.push(c);
-                    buf_chars.next();
-                    buf_index += 1;
-                } else {
-                    if buf_int.len() == 0 {
-                        return Err(Error::Syntax(real_index as usize, "Empty integer".into()));
-                    }
+        if n == 0 {
+            return Ok(false);
+        }
 
-                    if buf_int.len() > MAX_INT_BUF {
-                        return Err(Error::BigInt);
-                    }
+        chunk.truncate(n);
+        self.buf = chunk;
+        self.pos = 0;
 
-                    state = next_state.pop().ok_or(Error::StackUnderflow)?;
-                }
-            }
+        Ok(true)
+    }
 
-            _ => return Err(Error::UnexpectedState),
+    fn peek_byte(&mut self) -> Result<u8, Error> {
+        if self.fill()? {
+            Ok(self.buf[self.pos])
+        } else {
+            Err(Error::Eof)
         }
     }
 
-    if next_state.len() > 0 {
-        return Err(Error::Eof);
+    fn next_byte(&mut self) -> Result<u8, Error> {
+        let b = self.peek_byte()?;
+        self.pos += 1;
+        self.offset += 1;
+        Ok(b)
     }
+}
 
-    match state {
-        State::RootValInt => {
-            // Unwrap here because Int state already checks for EOF
-            let c = *buf_chars.next().unwrap();
-
-            if c != Token::End.into() {
-                return Err(Error::Syntax(file_size as usize - 1, "Expected 'e' token".into()));
-            }
+/// Builds a [`Value`] tree out of a [`Parser`]'s event stream.
+enum Builder {
+    Dict(BTreeMap<Value, Value>, Option<Value>),
+    List(Vec<Value>),
+}
 
-            let val = buf_int.parse::<i64>()
-                .map_err(|_| Error::Syntax(file_index as usize + buf_index,
-                                           "Invalid integer".into()))?;
-            root = Some(Value::Int(val));
+fn attach(stack: &mut Vec<Builder>, root: &mut Option<Value>, value: Value) -> Result<(), Error> {
+    match stack.last_mut() {
+        Some(Builder::Dict(map, pending)) => {
+            let key = pending.take().ok_or(Error::UnexpectedState)?;
+            map.insert(key, value);
         }
+        Some(Builder::List(list)) => list.push(value),
+        None => *root = Some(value),
+    }
 
-        State::RootValStr => root = Some(str_or_bytes(buf_str)),
+    Ok(())
+}
 
-        State::RootValDict => {
-            let dict = dict_stack.pop().ok_or(Error::StackUnderflow)?.borrow().clone();
+pub fn load(stream: &mut impl Read) -> Result<Value, Error> {
+    let mut parser = Parser::new(stream);
+    let mut stack: Vec<Builder> = Vec::new();
+    let mut root = None;
 
-            root = Some(Value::Dict(dict));
-        }
+    while let Some(event) = parser.next_event()? {
+        match event {
+            Event::BeginDict => stack.push(Builder::Dict(BTreeMap::new(), None)),
+            Event::BeginList => stack.push(Builder::List(Vec::new())),
 
-        State::RootValList => {
-            let list = list_stack.pop().ok_or(Error::StackUnderflow)?.borrow().clone();
+            Event::Key(k) => match stack.last_mut() {
+                Some(Builder::Dict(_, pending)) => *pending = Some(str_or_bytes(k.to_vec())),
+                _ => return Err(Error::UnexpectedState),
+            },
 
-            root = Some(Value::List(list));
-        }
+            Event::Int(n) => attach(&mut stack, &mut root, Value::Int(n))?,
+            Event::Bytes(b) => attach(&mut stack, &mut root, str_or_bytes(b.to_vec()))?,
 
-        _ => return Err(Error::UnexpectedState),
+            Event::End => {
+                let value = match stack.pop().ok_or(Error::StackUnderflow)? {
+                    Builder::Dict(map, _) => Value::Dict(map),
+                    Builder::List(list) => Value::List(list),
+                };
+
+                attach(&mut stack, &mut root, value)?;
+            }
+        }
     }
 
-    Ok(root.unwrap())
+    root.ok_or(Error::Empty)
 }
 
 pub fn load_str(s: &str) -> Result<Value, Error> {
@@ -762,7 +521,7 @@ impl Value {
             }};
 
             (pos) => {
-                full_selector.len() - selector.len() + 1;
+                full_selector.len() - selector.len()
             };
         }
 
@@ -948,6 +707,518 @@ impl Value {
 
         Ok((input, index.unwrap()))
     }
+
+    /// Mutable counterpart to [`select`](Self::select). Same syntax and errors; returns a
+    /// mutable reference to the selected value instead of a shared one.
+    pub fn select_mut(&mut self, mut selector: &str) -> Result<&mut Value, SelectError> {
+        if !self.is_dict() && !self.is_list() && !self.is_ref() {
+            return Err(SelectError::Primitive("<root>".into()));
+        }
+
+        if selector.is_empty() {
+            return Ok(self);
+        }
+
+        let full_selector = &selector[..];
+        let mut state = if self.is_dict() { TraverseState::Dict } else { TraverseState::List };
+        let mut value = self;
+
+        macro_rules! context {
+            () => {{
+                let pos = full_selector.len() - selector.len() + 1;
+                let c = &full_selector[..(pos - 1)];
+
+                if !c.is_empty() {
+                    c.into()
+                } else {
+                    "<root>".into()
+                }
+            }};
+        }
+
+        loop {
+            match state {
+                TraverseState::Dict => {
+                    if selector.chars().next().unwrap() == '[' {
+                        return Err(SelectError::Indexable(context!()));
+                    }
+
+                    let (rest, key) = Self::parse_key_selector(selector, full_selector)?;
+                    selector = rest;
+
+                    let map = match value {
+                        Value::Dict(m) => m,
+                        _ => unreachable!(),
+                    };
+
+                    let val = map.get_mut(&Value::Str(key.clone()))
+                        .ok_or_else(|| SelectError::Key(context!(), key))?;
+
+                    if selector.is_empty() {
+                        return Ok(val);
+                    } else if val.is_dict() {
+                        value = val;
+                    } else if val.is_list() {
+                        value = val;
+                        state = TraverseState::List;
+                    } else {
+                        return Err(SelectError::Primitive(context!()));
+                    }
+                }
+
+                TraverseState::List => {
+                    if selector.chars().next().unwrap() == '.' {
+                        return Err(SelectError::Subscriptable(context!()));
+                    }
+
+                    let (rest, index) = Self::parse_index_selector(selector, full_selector)?;
+                    selector = rest;
+
+                    let list = match value {
+                        Value::List(v) => v,
+                        _ => unreachable!(),
+                    };
+
+                    let val = list.get_mut(index)
+                        .ok_or_else(|| SelectError::Index(context!(), index))?;
+
+                    if selector.is_empty() {
+                        return Ok(val);
+                    } else if val.is_dict() {
+                        value = val;
+                        state = TraverseState::Dict;
+                    } else if val.is_list() {
+                        value = val;
+                    } else {
+                        return Err(SelectError::Primitive(context!()));
+                    }
+                }
+
+                // Done
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Tokenize a selector string into its key/index segments without touching a tree, so
+    /// [`entry`](Self::entry) can look as far ahead as it needs before deciding whether the
+    /// target already exists.
+    fn parse_selector(selector: &str) -> Result<Vec<(PathSegment, String)>, SelectError> {
+        let full_selector = selector;
+        let mut rest = selector;
+        let mut segments = Vec::new();
+
+        while !rest.is_empty() {
+            let c = rest.chars().next().unwrap();
+
+            if c == '.' {
+                let (tail, key) = Self::parse_key_selector(rest, full_selector)?;
+                rest = tail;
+                let consumed = full_selector[..(full_selector.len() - rest.len())].to_string();
+                segments.push((PathSegment::Key(key), consumed));
+            } else if c == '[' {
+                let (tail, index) = Self::parse_index_selector(rest, full_selector)?;
+                rest = tail;
+                let consumed = full_selector[..(full_selector.len() - rest.len())].to_string();
+                segments.push((PathSegment::Index(index), consumed));
+            } else {
+                let pos = full_selector.len() - rest.len() + 1;
+                let context = if pos > 1 { full_selector[..(pos - 1)].into() } else { "<root>".into() };
+
+                return Err(SelectError::Syntax(context, pos, "Expected '.' or '['".into()));
+            }
+        }
+
+        Ok(segments)
+    }
+
+    /// Look up `selector` like [`select_mut`](Self::select_mut), but instead of failing when
+    /// the path doesn't exist, return a handle that can create it. Modeled on
+    /// `BTreeMap::entry`: dict keys missing along the way are vacant and get `Value::Dict`
+    /// levels auto-created on `or_insert`; a list index exactly at the end of the list is
+    /// vacant too and gets appended. Any other kind of mismatch (an out-of-bounds index deeper
+    /// than "append", or a key/index used against the wrong container type) is still an error,
+    /// same as `select_mut`.
+    pub fn entry<'a>(&'a mut self, selector: &str) -> Result<Entry<'a>, SelectError> {
+        if !self.is_dict() && !self.is_list() {
+            return Err(SelectError::Primitive("<root>".into()));
+        }
+
+        if selector.is_empty() {
+            return Ok(Entry::Occupied(OccupiedEntry { value: self }));
+        }
+
+        let segments = Self::parse_selector(selector)?;
+        let mut value = self;
+
+        for i in 0..segments.len() {
+            let context = if i == 0 { "<root>".to_string() } else { segments[i - 1].1.clone() };
+
+            match (value, &segments[i].0) {
+                (Value::Dict(map), PathSegment::Key(key)) => {
+                    if map.contains_key(&Value::Str(key.clone())) {
+                        value = map.get_mut(&Value::Str(key.clone())).unwrap();
+                    } else {
+                        let nested = segments[(i + 1)..].iter().map(|(s, _)| match s {
+                            PathSegment::Key(k) => Ok(k.clone()),
+                            PathSegment::Index(_) => Err(SelectError::Indexable(context.clone())),
+                        }).collect::<Result<Vec<_>, _>>()?;
+
+                        return Ok(Entry::Vacant(VacantEntry::Dict {
+                            parent: map,
+                            key: key.clone(),
+                            nested,
+                        }));
+                    }
+                }
+
+                (Value::List(list), PathSegment::Index(index)) => {
+                    if *index < list.len() {
+                        value = &mut list[*index];
+                    } else if *index == list.len() && i == segments.len() - 1 {
+                        return Ok(Entry::Vacant(VacantEntry::List { parent: list }));
+                    } else {
+                        return Err(SelectError::Index(context, *index));
+                    }
+                }
+
+                (Value::Dict(_), PathSegment::Index(_)) => return Err(SelectError::Indexable(context)),
+                (Value::List(_), PathSegment::Key(_)) => return Err(SelectError::Subscriptable(context)),
+                _ => return Err(SelectError::Primitive(context)),
+            }
+        }
+
+        Ok(Entry::Occupied(OccupiedEntry { value }))
+    }
+
+    /// Visit every node in this tree, calling `f` with each node's fully-qualified selector
+    /// path (the same `.key.key[idx]` syntax [`select`](Self::select) parses; the root is
+    /// `""`) and a reference to the node. Dict children are visited in key order, so a walk
+    /// of the same document is stable across runs. `f`'s return value decides whether to
+    /// descend into that node's children, skip them, or abort the whole walk early.
+    pub fn walk<F: FnMut(&str, &Value) -> WalkControl>(&self, mut f: F) {
+        fn go<F: FnMut(&str, &Value) -> WalkControl>(path: &str, value: &Value, f: &mut F) -> WalkControl {
+            let control = f(path, value);
+
+            if control != WalkControl::Continue {
+                return control;
+            }
+
+            match value {
+                Value::Dict(map) => {
+                    for (key, val) in map {
+                        let child = format!("{}.{}", path, escape_key(key.to_str().expect("dict keys are always strings")));
+
+                        if go(&child, val, f) == WalkControl::Stop {
+                            return WalkControl::Stop;
+                        }
+                    }
+                }
+
+                Value::List(list) => {
+                    for (i, val) in list.iter().enumerate() {
+                        let child = format!("{}[{}]", path, i);
+
+                        if go(&child, val, f) == WalkControl::Stop {
+                            return WalkControl::Stop;
+                        }
+                    }
+                }
+
+                _ => {}
+            }
+
+            WalkControl::Continue
+        }
+
+        go("", self, &mut f);
+    }
+
+    /// Mutable counterpart to [`walk`](Self::walk). Same path syntax, visiting order, and
+    /// control semantics, except `f` gets a mutable reference so callers can bulk-transform
+    /// matching nodes in place instead of rebuilding the tree around `select_mut` calls.
+    pub fn walk_mut<F: FnMut(&str, &mut Value) -> WalkControl>(&mut self, mut f: F) {
+        fn go<F: FnMut(&str, &mut Value) -> WalkControl>(path: &str, value: &mut Value, f: &mut F) -> WalkControl {
+            let control = f(path, value);
+
+            if control != WalkControl::Continue {
+                return control;
+            }
+
+            match value {
+                Value::Dict(map) => {
+                    for (key, val) in map.iter_mut() {
+                        let child = format!("{}.{}", path, escape_key(key.to_str().expect("dict keys are always strings")));
+
+                        if go(&child, val, f) == WalkControl::Stop {
+                            return WalkControl::Stop;
+                        }
+                    }
+                }
+
+                Value::List(list) => {
+                    for (i, val) in list.iter_mut().enumerate() {
+                        let child = format!("{}[{}]", path, i);
+
+                        if go(&child, val, f) == WalkControl::Stop {
+                            return WalkControl::Stop;
+                        }
+                    }
+                }
+
+                _ => {}
+            }
+
+            WalkControl::Continue
+        }
+
+        go("", self, &mut f);
+    }
+}
+
+/// Escape a raw dict key so it round-trips through [`Value::select`]'s selector syntax:
+/// `.`, `[`, and `\` all need a preceding backslash.
+fn escape_key(key: &str) -> String {
+    let mut buf = String::with_capacity(key.len());
+
+    for c in key.chars() {
+        if c == '.' || c == '[' || c == '\\' {
+            buf.push('\\');
+        }
+
+        buf.push(c);
+    }
+
+    buf
+}
+
+/// The result of a callback passed to [`Value::walk`] or [`Value::walk_mut`]: whether to
+/// keep descending, skip the current node's children, or abort the walk entirely.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WalkControl {
+    Continue,
+    SkipChildren,
+    Stop,
+}
+
+/// Raised by [`Value::from_json`] when a JSON document can't be represented as bencode.
+#[derive(Debug)]
+pub enum JsonError {
+    NotAnInteger(f64),
+    IntegerOverflow,
+    InvalidBytesTag(String),
+    Unsupported(&'static str),
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_decode(s: &str) -> Result<Vec<u8>, String> {
+    if s.len() % 2 != 0 {
+        return Err("odd-length hex string".into());
+    }
+
+    (0..s.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..(i + 2)], 16).map_err(|_| format!("invalid hex digit at {}", i)))
+        .collect()
+}
+
+impl Value {
+    /// Convert this value to a `serde_json::Value`. `Str` becomes a JSON string, `Bytes`
+    /// becomes a tagged object `{"$bytes": "<hex>"}` so non-UTF-8 byte strings (torrent
+    /// `pieces`, most notably) still round-trip losslessly through [`from_json`](Self::from_json).
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::{Map, Number, Value as Json};
+
+        match self {
+            Value::Int(i) => Json::Number(Number::from(*i)),
+            Value::Str(s) => Json::String(s.clone()),
+
+            Value::Bytes(b) => {
+                let mut tag = Map::new();
+                tag.insert("$bytes".into(), Json::String(hex_encode(b)));
+                Json::Object(tag)
+            }
+
+            Value::Dict(m) => {
+                let mut obj = Map::new();
+
+                for (k, v) in m {
+                    let key = k.to_str().expect("dict keys are always strings").to_string();
+                    obj.insert(key, v.to_json());
+                }
+
+                Json::Object(obj)
+            }
+
+            Value::List(v) => Json::Array(v.iter().map(Value::to_json).collect()),
+            Value::DictRef(rc) => Value::Dict(rc.borrow().clone()).to_json(),
+            Value::ListRef(rc) => Value::List(rc.borrow().clone()).to_json(),
+        }
+    }
+
+    /// Parse a `serde_json::Value` into a bencode `Value`, reversing the policy used by
+    /// [`to_json`](Self::to_json). Numbers must be whole and fit in an `i64`, since that's all
+    /// a bencode integer can hold; `null` and booleans have no bencode equivalent.
+    pub fn from_json(json: &serde_json::Value) -> Result<Value, JsonError> {
+        use serde_json::Value as Json;
+
+        Ok(match json {
+            Json::Number(n) => {
+                if let Some(i) = n.as_i64() {
+                    Value::Int(i)
+                } else if n.is_u64() {
+                    return Err(JsonError::IntegerOverflow);
+                } else {
+                    // Numbers like `1e9` round-trip through serde_json as f64 even though
+                    // they're integral; only a genuine fractional part should be rejected.
+                    let f = n.as_f64().unwrap_or(f64::NAN);
+
+                    if f.fract() != 0.0 {
+                        return Err(JsonError::NotAnInteger(f));
+                    } else if f < i64::MIN as f64 || f > i64::MAX as f64 {
+                        return Err(JsonError::IntegerOverflow);
+                    } else {
+                        Value::Int(f as i64)
+                    }
+                }
+            }
+
+            Json::String(s) => Value::Str(s.clone()),
+
+            Json::Object(obj) if obj.len() == 1 && obj.contains_key("$bytes") => {
+                let hex = obj["$bytes"].as_str()
+                    .ok_or_else(|| JsonError::InvalidBytesTag("not a string".into()))?;
+
+                Value::Bytes(hex_decode(hex).map_err(JsonError::InvalidBytesTag)?)
+            }
+
+            Json::Object(obj) => {
+                let mut map = BTreeMap::new();
+
+                for (k, v) in obj {
+                    map.insert(Value::Str(k.clone()), Value::from_json(v)?);
+                }
+
+                Value::Dict(map)
+            }
+
+            Json::Array(arr) => Value::List(
+                arr.iter().map(Value::from_json).collect::<Result<_, _>>()?
+            ),
+
+            Json::Null => return Err(JsonError::Unsupported("null")),
+            Json::Bool(_) => return Err(JsonError::Unsupported("bool")),
+        })
+    }
+}
+
+impl From<Value> for serde_json::Value {
+    fn from(v: Value) -> Self {
+        v.to_json()
+    }
+}
+
+impl TryFrom<serde_json::Value> for Value {
+    type Error = JsonError;
+
+    fn try_from(json: serde_json::Value) -> Result<Self, Self::Error> {
+        Value::from_json(&json)
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotAnInteger(n) => write!(f, "Expected a whole number, got {}", n),
+            Self::IntegerOverflow => write!(f, "Number is too big to fit in an i64"),
+            Self::InvalidBytesTag(why) => write!(f, "Invalid '$bytes' tag: {}", why),
+            Self::Unsupported(kind) => write!(f, "JSON {} has no bencode equivalent", kind),
+        }
+    }
+}
+
+/// A single key or index making up a selector, tokenized ahead of time by
+/// [`Value::parse_selector`].
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// The result of [`Value::entry`]: either the path already resolves to a value
+/// ([`Occupied`](Entry::Occupied)), or it can be created ([`Vacant`](Entry::Vacant)).
+pub enum Entry<'a> {
+    Occupied(OccupiedEntry<'a>),
+    Vacant(VacantEntry<'a>),
+}
+
+pub struct OccupiedEntry<'a> {
+    value: &'a mut Value,
+}
+
+pub enum VacantEntry<'a> {
+    Dict { parent: &'a mut BTreeMap<Value, Value>, key: String, nested: Vec<String> },
+    List { parent: &'a mut Vec<Value> },
+}
+
+impl<'a> Entry<'a> {
+    /// Run `f` against the value if it's already present; no-op for a vacant entry.
+    pub fn and_modify<F: FnOnce(&mut Value)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(o) = &mut self {
+            f(o.value);
+        }
+
+        self
+    }
+
+    /// Return the existing value, or insert and return `default`.
+    pub fn or_insert(self, default: Value) -> &'a mut Value {
+        match self {
+            Entry::Occupied(o) => o.value,
+            Entry::Vacant(v) => v.insert(default),
+        }
+    }
+
+    /// Return the existing value, or insert and return the result of `f`.
+    pub fn or_insert_with<F: FnOnce() -> Value>(self, f: F) -> &'a mut Value {
+        match self {
+            Entry::Occupied(o) => o.value,
+            Entry::Vacant(v) => v.insert(f()),
+        }
+    }
+}
+
+impl<'a> VacantEntry<'a> {
+    fn insert(self, value: Value) -> &'a mut Value {
+        match self {
+            Self::List { parent } => {
+                parent.push(value);
+                parent.last_mut().unwrap()
+            }
+
+            Self::Dict { parent, key, nested } => {
+                let wrapped = nested.iter().rev().fold(value, |acc, k| {
+                    let mut m = BTreeMap::new();
+                    m.insert(Value::Str(k.clone()), acc);
+                    Value::Dict(m)
+                });
+
+                parent.insert(Value::Str(key.clone()), wrapped);
+
+                let mut cur = parent.get_mut(&Value::Str(key)).unwrap();
+
+                for k in &nested {
+                    cur = match cur {
+                        Value::Dict(m) => m.get_mut(&Value::Str(k.clone())).unwrap(),
+                        _ => unreachable!(),
+                    };
+                }
+
+                cur
+            }
+        }
+    }
 }
 
 impl<'a> ValueDisplay<'a> {
@@ -1171,14 +1442,14 @@ impl From<IoError> for Error {
     }
 }
 
-impl Into<u8> for Token {
-    fn into(self) -> u8 {
-        match self {
-            Self::Dict => 'd' as u8,
-            Self::Int => 'i' as u8,
-            Self::List => 'l' as u8,
-            Self::Colon => ':' as u8,
-            Self::End => 'e' as u8,
+impl From<Token> for u8 {
+    fn from(token: Token) -> u8 {
+        match token {
+            Token::Dict => 'd' as u8,
+            Token::Int => 'i' as u8,
+            Token::List => 'l' as u8,
+            Token::Colon => ':' as u8,
+            Token::End => 'e' as u8,
         }
     }
 }
@@ -1206,7 +1477,7 @@ impl TryFrom<u8> for Token {
 
 #[cfg(test)]
 mod tests {
-    use super::{BTreeMap, Value};
+    use super::{BTreeMap, Entry, Value, WalkControl};
 
     const DICT_VAL_INT: &'static str = "d3:fooi0e3:bari1e3:bazi2ee";
     const LIST_VAL_STR: &'static str = "l3:foo3:bar3:baze";
@@ -1299,6 +1570,61 @@ mod tests {
         check_value(DICT_MIXED, Value::Dict(root_map));
     }
 
+    #[test]
+    fn parser_yields_events_without_building_a_tree() {
+        use std::io::Cursor;
+        use super::{Event, Parser};
+
+        let mut parser = Parser::new(Cursor::new(DICT_VAL_INT));
+        let mut events = Vec::new();
+
+        while let Some(event) = parser.next_event().unwrap() {
+            events.push(match event {
+                Event::BeginDict => "BeginDict".to_string(),
+                Event::Key(k) => format!("Key({})", String::from_utf8_lossy(k)),
+                Event::BeginList => "BeginList".to_string(),
+                Event::Int(i) => format!("Int({})", i),
+                Event::Bytes(b) => format!("Bytes({})", String::from_utf8_lossy(b)),
+                Event::End => "End".to_string(),
+            });
+        }
+
+        assert_eq!(events, vec![
+            "BeginDict".to_string(),
+            "Key(foo)".to_string(),
+            "Int(0)".to_string(),
+            "Key(bar)".to_string(),
+            "Int(1)".to_string(),
+            "Key(baz)".to_string(),
+            "Int(2)".to_string(),
+            "End".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn parser_handles_strings_spanning_chunk_boundaries() {
+        use std::io::Cursor;
+        use super::Parser;
+
+        let payload = "x".repeat(5000);
+        let source = format!("{}:{}", payload.len(), payload);
+        let mut parser = Parser::new(Cursor::new(source.as_bytes()));
+
+        match parser.next_event().unwrap() {
+            Some(super::Event::Bytes(b)) => assert_eq!(b, payload.as_bytes()),
+            _ => panic!("expected a Bytes event"),
+        }
+
+        assert!(parser.next_event().unwrap().is_none());
+    }
+
+    #[test]
+    fn load_rejects_empty_input() {
+        use std::io::Cursor;
+
+        assert!(matches!(super::load(&mut Cursor::new(b"")), Err(super::Error::Empty)));
+    }
+
     #[test]
     fn select_dict_simple() {
         let mut map = BTreeMap::new();
@@ -1368,4 +1694,201 @@ mod tests {
         assert_eq!(dict.select(".buz.fghij[2]").unwrap(), &Value::Dict(fghij_map));
         assert_eq!(dict.select(".buz.fghij[2].wxyz").unwrap(), &Value::Int(0));
     }
+
+    #[test]
+    fn select_mut_dict_simple() {
+        let mut map = BTreeMap::new();
+        map.insert(Value::Str("foo".into()), Value::Int(0));
+        let mut dict = Value::Dict(map);
+
+        *dict.select_mut(".foo").unwrap() = Value::Int(42);
+
+        assert_eq!(dict.select(".foo").unwrap(), &Value::Int(42));
+    }
+
+    #[test]
+    fn select_mut_list_nested() {
+        let list = Value::List(vec![Value::Int(0), Value::Int(1), Value::Int(2)]);
+        let mut list = Value::List(vec![list]);
+
+        *list.select_mut("[0][1]").unwrap() = Value::Int(99);
+
+        assert_eq!(list.select("[0][1]").unwrap(), &Value::Int(99));
+    }
+
+    #[test]
+    fn entry_occupied_and_modify() {
+        let mut map = BTreeMap::new();
+        map.insert(Value::Str("foo".into()), Value::Int(0));
+        let mut dict = Value::Dict(map);
+
+        dict.entry(".foo").unwrap().and_modify(|v| *v = Value::Int(1));
+
+        assert_eq!(dict.select(".foo").unwrap(), &Value::Int(1));
+    }
+
+    #[test]
+    fn entry_vacant_creates_intermediate_dicts() {
+        let mut map = BTreeMap::new();
+        map.insert(Value::Str("buz".into()), Value::Dict(BTreeMap::new()));
+        let mut dict = Value::Dict(map);
+
+        let inserted = dict.entry(".buz.new.deep").unwrap().or_insert(Value::Int(7));
+        assert_eq!(inserted, &Value::Int(7));
+
+        assert_eq!(dict.select(".buz.new.deep").unwrap(), &Value::Int(7));
+    }
+
+    #[test]
+    fn entry_vacant_appends_to_list() {
+        let mut list = Value::List(vec![Value::Int(0), Value::Int(1)]);
+
+        let inserted = list.entry("[2]").unwrap().or_insert(Value::Int(2));
+        assert_eq!(inserted, &Value::Int(2));
+
+        assert_eq!(list.select("[2]").unwrap(), &Value::Int(2));
+    }
+
+    #[test]
+    fn entry_vacant_out_of_bounds_index_errors() {
+        let mut list = Value::List(vec![Value::Int(0)]);
+
+        assert!(matches!(list.entry("[5]"), Err(super::SelectError::Index(_, 5))));
+    }
+
+    #[test]
+    fn entry_or_insert_with_on_occupied_keeps_existing() {
+        let mut map = BTreeMap::new();
+        map.insert(Value::Str("foo".into()), Value::Int(0));
+        let mut dict = Value::Dict(map);
+
+        let value = dict.entry(".foo").unwrap().or_insert_with(|| Value::Int(123));
+        assert_eq!(value, &Value::Int(0));
+
+        if let Entry::Vacant(_) = dict.entry(".bar").unwrap() {
+            // expected: ".bar" doesn't exist yet
+        } else {
+            panic!("expected a vacant entry for '.bar'");
+        }
+    }
+
+    #[test]
+    fn to_json_tags_non_utf8_bytes() {
+        let mut map = BTreeMap::new();
+        map.insert(Value::Str("name".into()), Value::Str("foo".into()));
+        map.insert(Value::Str("pieces".into()), Value::Bytes(vec![0xff, 0x00, 0xab]));
+        let dict = Value::Dict(map);
+
+        let json = dict.to_json();
+        assert_eq!(json["name"], serde_json::json!("foo"));
+        assert_eq!(json["pieces"], serde_json::json!({"$bytes": "ff00ab"}));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut map = BTreeMap::new();
+        map.insert(Value::Str("length".into()), Value::Int(1024));
+        map.insert(Value::Str("pieces".into()), Value::Bytes(vec![0xde, 0xad, 0xbe, 0xef]));
+        map.insert(Value::Str("files".into()), Value::List(vec![Value::Str("a".into()), Value::Int(2)]));
+        let dict = Value::Dict(map);
+
+        let json = dict.to_json();
+        let back = Value::from_json(&json).unwrap();
+
+        assert_eq!(back, dict);
+    }
+
+    #[test]
+    fn walk_visits_every_node_in_key_order() {
+        let mut buz_map = BTreeMap::new();
+        buz_map.insert(Value::Str("a".into()), Value::Int(1));
+        buz_map.insert(Value::Str("b".into()), Value::Int(2));
+
+        let mut root_map = BTreeMap::new();
+        root_map.insert(Value::Str("buz".into()), Value::Dict(buz_map));
+        root_map.insert(Value::Str("zyx".into()), Value::List(vec![Value::Int(0), Value::Int(1)]));
+        let dict = Value::Dict(root_map);
+
+        let mut paths = Vec::new();
+        dict.walk(|path, _| {
+            paths.push(path.to_string());
+            WalkControl::Continue
+        });
+
+        assert_eq!(paths, vec![
+            "",
+            ".buz",
+            ".buz.a",
+            ".buz.b",
+            ".zyx",
+            ".zyx[0]",
+            ".zyx[1]",
+        ]);
+    }
+
+    #[test]
+    fn walk_skip_children_and_stop() {
+        let mut root_map = BTreeMap::new();
+        root_map.insert(Value::Str("a".into()), Value::List(vec![Value::Int(0), Value::Int(1)]));
+        root_map.insert(Value::Str("b".into()), Value::Int(2));
+        let dict = Value::Dict(root_map);
+
+        let mut paths = Vec::new();
+        dict.walk(|path, value| {
+            paths.push(path.to_string());
+
+            if value.is_list() {
+                WalkControl::SkipChildren
+            } else {
+                WalkControl::Continue
+            }
+        });
+
+        assert_eq!(paths, vec!["", ".a", ".b"]);
+
+        let mut seen = Vec::new();
+        dict.walk(|path, _| {
+            seen.push(path.to_string());
+            WalkControl::Stop
+        });
+
+        assert_eq!(seen, vec![""]);
+    }
+
+    #[test]
+    fn walk_mut_rewrites_matching_ints() {
+        let mut list = Value::List(vec![Value::Int(1), Value::Str("skip".into()), Value::Int(2)]);
+
+        list.walk_mut(|_, value| {
+            if let Value::Int(i) = value {
+                *i *= 10;
+            }
+
+            WalkControl::Continue
+        });
+
+        assert_eq!(list.select("[0]").unwrap(), &Value::Int(10));
+        assert_eq!(list.select("[2]").unwrap(), &Value::Int(20));
+    }
+
+    #[test]
+    fn from_json_rejects_fractional_and_oversized_numbers() {
+        assert!(matches!(
+            Value::from_json(&serde_json::json!(1.5)),
+            Err(super::JsonError::NotAnInteger(_))
+        ));
+
+        assert!(matches!(
+            Value::from_json(&serde_json::json!(u64::MAX)),
+            Err(super::JsonError::IntegerOverflow)
+        ));
+    }
+
+    #[test]
+    fn from_json_accepts_whole_numbers_represented_as_f64() {
+        // `1e9` and similar exponent notation round-trip through serde_json as f64 even
+        // though they're whole numbers, and shouldn't be rejected as fractional.
+        assert_eq!(Value::from_json(&serde_json::json!(1e9)).unwrap(), Value::Int(1_000_000_000));
+        assert_eq!(Value::from_json(&serde_json::json!(100000000000i64)).unwrap(), Value::Int(100000000000));
+    }
 }
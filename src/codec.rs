@@ -0,0 +1,148 @@
+//! Self-contained Base64/Base32 codecs (RFC 4648, standard alphabets, `=` padding).
+//!
+//! These exist so binary `Bytes` values (e.g. a torrent's `pieces` field) can be shown and
+//! set as text without round-tripping through the JSON path, which mangles non-UTF-8 data.
+
+use std::fmt;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[derive(Debug)]
+pub enum DecodeError {
+    InvalidChar(char),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidChar(c) => write!(f, "Invalid character '{}'", c),
+        }
+    }
+}
+
+/// Encode `data` as standard Base64 with `=` padding.
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+
+    for chunk in data.chunks(3) {
+        let b0 = chunk[0];
+        let b1 = *chunk.get(1).unwrap_or(&0);
+        let b2 = *chunk.get(2).unwrap_or(&0);
+
+        out.push(BASE64_ALPHABET[(b0 >> 2) as usize] as char);
+        out.push(BASE64_ALPHABET[(((b0 & 0x03) << 4) | (b1 >> 4)) as usize] as char);
+
+        if chunk.len() > 1 {
+            out.push(BASE64_ALPHABET[(((b1 & 0x0f) << 2) | (b2 >> 6)) as usize] as char);
+        } else {
+            out.push('=');
+        }
+
+        if chunk.len() > 2 {
+            out.push(BASE64_ALPHABET[(b2 & 0x3f) as usize] as char);
+        } else {
+            out.push('=');
+        }
+    }
+
+    out
+}
+
+/// Decode standard Base64. When `ignore_garbage` is set, whitespace and any character
+/// outside the alphabet/padding is skipped instead of rejected (mirroring `base64 -i`).
+pub fn base64_decode(s: &str, ignore_garbage: bool) -> Result<Vec<u8>, DecodeError> {
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+
+    for c in s.chars() {
+        if c == '=' {
+            break;
+        }
+
+        let value = match BASE64_ALPHABET.iter().position(|&a| a as char == c) {
+            Some(v) => v as u32,
+            None if ignore_garbage => continue,
+            None => return Err(DecodeError::InvalidChar(c)),
+        };
+
+        acc = (acc << 6) | value;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Encode `data` as standard (RFC 4648) Base32 with `=` padding.
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 4) / 5 * 8);
+
+    for chunk in data.chunks(5) {
+        let mut buf = [0u8; 5];
+        buf[..chunk.len()].copy_from_slice(chunk);
+
+        let bits: u64 = (buf[0] as u64) << 32
+            | (buf[1] as u64) << 24
+            | (buf[2] as u64) << 16
+            | (buf[3] as u64) << 8
+            | (buf[4] as u64);
+
+        let out_len = match chunk.len() {
+            1 => 2,
+            2 => 4,
+            3 => 5,
+            4 => 7,
+            _ => 8,
+        };
+
+        for i in 0..8 {
+            if i < out_len {
+                let shift = 35 - i * 5;
+                let index = ((bits >> shift) & 0x1f) as usize;
+                out.push(BASE32_ALPHABET[index] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+/// Decode standard Base32. When `ignore_garbage` is set, whitespace and any character
+/// outside the alphabet/padding is skipped instead of rejected.
+pub fn base32_decode(s: &str, ignore_garbage: bool) -> Result<Vec<u8>, DecodeError> {
+    let mut out = Vec::with_capacity(s.len() / 8 * 5);
+    let mut acc = 0u64;
+    let mut bits = 0u32;
+
+    for c in s.chars() {
+        if c == '=' {
+            break;
+        }
+
+        let upper = c.to_ascii_uppercase();
+        let value = match BASE32_ALPHABET.iter().position(|&a| a as char == upper) {
+            Some(v) => v as u64,
+            None if ignore_garbage => continue,
+            None => return Err(DecodeError::InvalidChar(c)),
+        };
+
+        acc = (acc << 5) | value;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+
+    Ok(out)
+}
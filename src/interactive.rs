@@ -9,12 +9,14 @@ use rustyline::{
     Editor,
 };
 
+use crate::codec;
+
 pub enum Error {
     Io(IoError),
     InvalidFile(String),
 }
 
-enum CmdError {
+pub(crate) enum CmdError {
     Io(IoError),
     UnknownCommand(String),
     Command(String),
@@ -26,14 +28,57 @@ enum CmdError {
     ArgCountMax(usize),
 }
 
-struct State {
+pub(crate) struct State {
     path: PathBuf,
     data: Option<BencValue>,
-    changed: bool,
+    pub(crate) changed: bool,
+    read_only: bool,
+    output: Option<PathBuf>,
+    format: Format,
+    saved_hash: u64,
+    undo_stack: Vec<BencValue>,
+    redo_stack: Vec<BencValue>,
+}
+
+/// Maximum number of snapshots kept on the undo stack before the oldest is dropped.
+const UNDO_LIMIT: usize = 32;
+
+/// Encoding used when a `State` saves its document to disk.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Format {
+    Bencode,
+    Json,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self::Bencode
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bencode" => Ok(Self::Bencode),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown format '{}', expected 'bencode' or 'json'", s)),
+        }
+    }
 }
 
-pub fn interactive<P>(file: P) -> Result<(), Error> where P: AsRef<Path> {
-    let mut state = State::new(file.as_ref())?;
+pub fn interactive<P>(
+    file: P,
+    read_only: bool,
+    output: Option<PathBuf>,
+    format: Format,
+) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+{
+    println!("Loading {}", file.as_ref().display());
+    let mut state = State::new(file.as_ref(), read_only, output, format)?;
     let mut rl = Editor::<()>::new();
 
     loop {
@@ -43,24 +88,9 @@ pub fn interactive<P>(file: P) -> Result<(), Error> where P: AsRef<Path> {
         match readline {
             Ok(line) => {
                 let input = line.trim();
-                let space_at = input.find(' ');
                 rl.add_history_entry(input);
 
-                let (cmd, argbuf) = if let Some(space_at) = space_at {
-                    let s = input.split_at(space_at);
-                    (Some(s.0), s.1)
-                } else {
-                    (if input.len() > 0 { Some(input) } else { None }, "")
-                };
-
-                if let Some(cmd) = cmd {
-                    let cmd = cmd.to_lowercase();
-                    let argbuf = if argbuf.len() > 0 {
-                        &argbuf[1..]
-                    } else {
-                        argbuf
-                    };
-
+                if let Some((cmd, argbuf)) = split_command(input) {
                     match interactive_cmd(&mut state, cmd, argbuf) {
                         Ok(keep_running) => if !keep_running { break; },
                         Err(e) => eprintln!("Error: {}", e),
@@ -70,17 +100,12 @@ pub fn interactive<P>(file: P) -> Result<(), Error> where P: AsRef<Path> {
 
             Err(ReadlineError::Interrupted) => {}
             Err(ReadlineError::Eof) => {
-                use std::fs::File;
-
                 if state.changed {
                     let confirm = prompt_confirm("There were changes made, do you want to save them?")?;
 
                     if confirm {
-                        let path = &state.path.canonicalize()?;
-                        let mut file = File::create(path)?;
-
                         println!("Saving...");
-                        state.data.as_ref().unwrap().encode(&mut file)?;
+                        state.save()?;
                     }
                 }
 
@@ -96,11 +121,45 @@ pub fn interactive<P>(file: P) -> Result<(), Error> where P: AsRef<Path> {
     Ok(())
 }
 
-fn interactive_cmd(state: &mut State, cmd: String, argbuf: &str) -> Result<bool, CmdError> {
+/// Split a raw input line into a lowercased command name and its argument buffer.
+///
+/// Returns `None` if the line is empty. Shared by the interactive loop and the batch
+/// script runner so both dispatch through the same rules.
+pub(crate) fn split_command(input: &str) -> Option<(String, &str)> {
+    let space_at = input.find(' ');
+
+    let (cmd, argbuf) = if let Some(space_at) = space_at {
+        let s = input.split_at(space_at);
+        (Some(s.0), &s.1[1..])
+    } else {
+        (if input.len() > 0 { Some(input) } else { None }, "")
+    };
+
+    cmd.map(|cmd| (cmd.to_lowercase(), argbuf))
+}
+
+const MUTATING_CMDS: &[&str] = &["set", "clear", "remove", "insert", "append"];
+
+pub(crate) fn interactive_cmd(state: &mut State, cmd: String, argbuf: &str) -> Result<bool, CmdError> {
     let args = parse_args(argbuf)?;
+    let is_mutating = MUTATING_CMDS.contains(&cmd.as_str());
 
-    Ok(match cmd.as_ref() {
+    if state.read_only && is_mutating {
+        return Err(CmdError::Command(
+            "bencedit was started with --read-only, mutating commands are disabled".into()
+        ));
+    }
+
+    let pre_mutation = if is_mutating {
+        Some((state.data.clone().unwrap(), hash_value(state.data.as_ref().unwrap())))
+    } else {
+        None
+    };
+
+    let keep_running = match cmd.as_ref() {
         "show" => {
+            let (encoding, args) = split_encoding_flag(&args);
+
             if args.len() > 1 {
                 return Err(CmdError::ArgCountMax(1));
             }
@@ -108,7 +167,16 @@ fn interactive_cmd(state: &mut State, cmd: String, argbuf: &str) -> Result<bool,
             let data = state.data.as_ref().unwrap();
             let selector = args.iter().next().map(|s| s.as_str()).unwrap_or("");
             let value = data.select(selector)?;
-            println!("{}", value);
+
+            if let Some(encoding) = encoding {
+                let bytes = value.to_bytes().ok_or_else(|| {
+                    CmdError::Command("Selected value is not a byte string".into())
+                })?;
+
+                println!("{}", encoding.encode(bytes));
+            } else {
+                println!("{}", value);
+            }
 
             true
         }
@@ -116,6 +184,8 @@ fn interactive_cmd(state: &mut State, cmd: String, argbuf: &str) -> Result<bool,
         "set" => {
             use nanoserde::DeJson;
 
+            let (encoding, args) = split_encoding_flag(&args);
+
             if args.len() != 2 {
                 return Err(CmdError::ArgCount(2));
             }
@@ -123,18 +193,25 @@ fn interactive_cmd(state: &mut State, cmd: String, argbuf: &str) -> Result<bool,
             let old_hash = hash_value(state.data.as_ref().unwrap().select(&args[0])?);
             let old_value = state.data.as_mut().unwrap().select_mut(&args[0])?;
 
-            match BencValue::deserialize_json(&args[1]) {
-                Ok(value) => {
-                    let new_hash = hash_value(&value);
-                    *old_value = value;
+            let value = if let Some(encoding) = encoding {
+                let bytes = encoding.decode(&args[1])
+                    .map_err(|e| CmdError::Command(format!("{}", e)))?;
 
-                    if new_hash != old_hash {
-                        state.changed = true;
-                    }
-                },
-                Err(e) => return Err(CmdError::Command(
-                    format!("{}, at {}:{}", e.msg.trim_end(), e.line + 1, e.col)
-                )),
+                BencValue::Bytes(bytes)
+            } else {
+                match BencValue::deserialize_json(&args[1]) {
+                    Ok(value) => value,
+                    Err(e) => return Err(CmdError::Command(
+                        format!("{}, at {}:{}", e.msg.trim_end(), e.line + 1, e.col)
+                    )),
+                }
+            };
+
+            let new_hash = hash_value(&value);
+            *old_value = value;
+
+            if new_hash != old_hash {
+                state.changed = true;
             }
 
             true
@@ -152,6 +229,7 @@ fn interactive_cmd(state: &mut State, cmd: String, argbuf: &str) -> Result<bool,
             };
 
             if confirm {
+                println!("Loading {}", state.path.display());
                 state.reload_data()
                     .map(|_| true)
                     .map_err(|e| CmdError::Command(format!("{}", e)))?;
@@ -163,19 +241,13 @@ fn interactive_cmd(state: &mut State, cmd: String, argbuf: &str) -> Result<bool,
         }
 
         "save" => {
-            use std::fs::File;
-
             if !state.changed {
                 println!("No changes to be saved.");
                 return Ok(true);
             }
 
-            let path = &state.path.canonicalize()?;
-            let mut file = File::create(path)?;
-
             println!("Saving...");
-            state.data.as_ref().unwrap().encode(&mut file)?;
-            state.changed = false;
+            state.save()?;
 
             true
         }
@@ -198,8 +270,9 @@ fn interactive_cmd(state: &mut State, cmd: String, argbuf: &str) -> Result<bool,
                 let mut file = File::create(path.clone())?;
 
                 println!("Saving to {}...", path.display());
-                state.data.as_ref().unwrap().encode(&mut file)?;
+                write_formatted(state.data.as_ref().unwrap(), state.format, &mut file)?;
                 state.changed = false;
+                state.saved_hash = hash_value(state.data.as_ref().unwrap());
             }
 
             true
@@ -313,10 +386,238 @@ fn interactive_cmd(state: &mut State, cmd: String, argbuf: &str) -> Result<bool,
             true
         }
 
+        "diff" => {
+            if args.is_empty() {
+                return Err(CmdError::ArgCountMin(1));
+            }
+
+            if args.len() > 2 {
+                return Err(CmdError::ArgCountMax(2));
+            }
+
+            let other = load_file(Path::new(&args[0]))?;
+            let selector = args.get(1).map(|s| s.as_str()).unwrap_or("");
+
+            let a = state.data.as_ref().unwrap().select(selector)?;
+            let b = other.select(selector)?;
+
+            let mut changes = Vec::new();
+            diff_values(selector, a, b, &mut changes)?;
+
+            if changes.is_empty() {
+                println!("No differences.");
+            } else {
+                for change in &changes {
+                    println!("{}", change);
+                }
+            }
+
+            true
+        }
+
+        "undo" => {
+            if !args.is_empty() {
+                return Err(CmdError::ArgCount(0));
+            }
+
+            match state.undo_stack.pop() {
+                Some(previous) => {
+                    let current = state.data.replace(previous).unwrap();
+                    state.redo_stack.push(current);
+                    state.changed = hash_value(state.data.as_ref().unwrap()) != state.saved_hash;
+                }
+                None => println!("Nothing to undo."),
+            }
+
+            true
+        }
+
+        "redo" => {
+            if !args.is_empty() {
+                return Err(CmdError::ArgCount(0));
+            }
+
+            match state.redo_stack.pop() {
+                Some(next) => {
+                    let current = state.data.replace(next).unwrap();
+                    state.undo_stack.push(current);
+                    state.changed = hash_value(state.data.as_ref().unwrap()) != state.saved_hash;
+                }
+                None => println!("Nothing to redo."),
+            }
+
+            true
+        }
+
         "quit" | "exit" | "q" => false,
 
         _ => return Err(CmdError::UnknownCommand(cmd)),
-    })
+    };
+
+    if let Some((snapshot, pre_hash)) = pre_mutation {
+        if hash_value(state.data.as_ref().unwrap()) != pre_hash {
+            state.push_undo(snapshot);
+        }
+    }
+
+    Ok(keep_running)
+}
+
+/// Load a bencode document from `path`, used as the comparison target of `diff` and by the
+/// one-shot `get`/`set`/`validate` subcommands that operate without a `State`.
+pub(crate) fn load_file(path: &Path) -> Result<BencValue, CmdError> {
+    use std::fs::File;
+    use bencode::load;
+
+    let mut fp = File::open(path)?;
+    load(&mut fp).map_err(|e| CmdError::Command(format!("{}", e)))
+}
+
+/// A single dict key or list index, used to name a child while diffing two values.
+#[derive(Clone, PartialEq)]
+enum Member {
+    Key(String),
+    Index(usize),
+}
+
+impl Member {
+    /// The selector fragment that reaches this member from its immediate parent, suitable for
+    /// a one-hop `select` off the parent value.
+    fn relative(&self) -> String {
+        match self {
+            Self::Key(k) => format!(".{}", escape_key(k)),
+            Self::Index(i) => format!("[{}]", i),
+        }
+    }
+
+    /// Append this member onto a full selector path.
+    fn append_to(&self, path: &str) -> String {
+        match self {
+            Self::Key(k) if path.is_empty() => escape_key(k),
+            Self::Key(k) => format!("{}.{}", path, escape_key(k)),
+            Self::Index(i) => format!("{}[{}]", path, i),
+        }
+    }
+}
+
+/// Escape a dict key so it round-trips through the selector syntax: a literal `.`, `[`, or `\`
+/// in the key would otherwise be read as a path separator, so each is backslash-escaped.
+fn escape_key(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+
+    for c in key.chars() {
+        if matches!(c, '.' | '[' | '\\') {
+            out.push('\\');
+        }
+
+        out.push(c);
+    }
+
+    out
+}
+
+/// The immediate children of a container value, each tagged with its `hash_value` fingerprint
+/// so unchanged subtrees can be skipped without descending into them.
+fn children(value: &BencValue) -> Vec<(Member, u64)> {
+    use bencode::TraverseAction;
+
+    let mut out = Vec::new();
+
+    let _ = value.traverse::<_, ()>(|key, index, parent, child, _| {
+        if parent == value {
+            if let Some(child) = child {
+                let member = if let Some(key) = key {
+                    Member::Key(key.to_string())
+                } else if let Some(index) = index {
+                    Member::Index(index)
+                } else {
+                    return Ok(TraverseAction::Continue);
+                };
+
+                out.push((member, hash_value(child)));
+            }
+        }
+
+        Ok(TraverseAction::Continue)
+    });
+
+    out
+}
+
+/// Structurally compare `a` and `b`, appending a human-readable line to `out` for every added
+/// key/index, removed entry, and changed leaf value, named by its full selector path rooted
+/// at `path`. Containers whose `hash_value` fingerprint matches are skipped without descending.
+fn diff_values(path: &str, a: &BencValue, b: &BencValue, out: &mut Vec<String>) -> Result<(), CmdError> {
+    if a.is_container() && b.is_container() {
+        if hash_value(a) == hash_value(b) {
+            return Ok(());
+        }
+
+        let a_children = children(a);
+        let b_children = children(b);
+
+        for (member, a_hash) in &a_children {
+            match b_children.iter().find(|(m, _)| m == member) {
+                Some((_, b_hash)) => {
+                    if a_hash != b_hash {
+                        let a_child = a.select(&member.relative())?;
+                        let b_child = b.select(&member.relative())?;
+                        diff_values(&member.append_to(path), a_child, b_child, out)?;
+                    }
+                }
+                None => out.push(format!("- {}", member.append_to(path))),
+            }
+        }
+
+        for (member, _) in &b_children {
+            if !a_children.iter().any(|(m, _)| m == member) {
+                out.push(format!("+ {}", member.append_to(path)));
+            }
+        }
+    } else if hash_value(a) != hash_value(b) {
+        out.push(format!("{}: {} -> {}", path, a, b));
+    }
+
+    Ok(())
+}
+
+/// Which binary-safe text encoding `show`/`set` should use for a `Bytes` value, selected by
+/// a leading `--base64`/`--base32` flag.
+enum BytesEncoding {
+    Base64,
+    Base32,
+}
+
+impl BytesEncoding {
+    fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "--base64" => Some(Self::Base64),
+            "--base32" => Some(Self::Base32),
+            _ => None,
+        }
+    }
+
+    fn encode(&self, data: &[u8]) -> String {
+        match self {
+            Self::Base64 => codec::base64_encode(data),
+            Self::Base32 => codec::base32_encode(data),
+        }
+    }
+
+    fn decode(&self, data: &str) -> Result<Vec<u8>, codec::DecodeError> {
+        match self {
+            Self::Base64 => codec::base64_decode(data, true),
+            Self::Base32 => codec::base32_decode(data, true),
+        }
+    }
+}
+
+/// Strip a leading `--base64`/`--base32` flag off a command's arguments, if present.
+fn split_encoding_flag(args: &[String]) -> (Option<BytesEncoding>, &[String]) {
+    match args.first().and_then(|a| BytesEncoding::from_flag(a)) {
+        Some(encoding) => (Some(encoding), &args[1..]),
+        None => (None, args),
+    }
 }
 
 fn parse_args(buf: &str) -> Result<Vec<String>, CmdError> {
@@ -399,6 +700,20 @@ fn prompt_confirm(prompt: &str) -> Result<bool, IoError> {
     })
 }
 
+/// Write `data` to `out` using the requested save `Format`.
+pub(crate) fn write_formatted(data: &BencValue, format: Format, out: &mut impl Write) -> Result<(), IoError> {
+    match format {
+        Format::Bencode => data.encode(out)?,
+        Format::Json => {
+            use nanoserde::SerJson;
+
+            out.write_all(data.serialize_json().as_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
 fn hash_value(root: &BencValue) -> u64 {
     use std::{
         collections::hash_map::DefaultHasher,
@@ -446,31 +761,74 @@ fn hash_value(root: &BencValue) -> u64 {
 }
 
 impl State {
-    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self, Error> {
+    pub fn new<P: Into<PathBuf>>(
+        path: P,
+        read_only: bool,
+        output: Option<PathBuf>,
+        format: Format,
+    ) -> Result<Self, Error> {
         let mut me = Self {
             path: path.into(),
             data: None,
             changed: false,
+            read_only,
+            output,
+            format,
+            saved_hash: 0,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
         };
 
         me.reload_data()?;
         Ok(me)
     }
 
+    /// Write the current document to `self.output` (or `self.path` if unset), marking the
+    /// state unchanged.
+    pub(crate) fn save(&mut self) -> Result<(), Error> {
+        use std::fs::File;
+
+        let path = match &self.output {
+            Some(output) => output.clone(),
+            None => self.path.canonicalize()?,
+        };
+        let mut file = File::create(path)?;
+
+        write_formatted(self.data.as_ref().unwrap(), self.format, &mut file)?;
+        self.changed = false;
+        self.saved_hash = hash_value(self.data.as_ref().unwrap());
+
+        Ok(())
+    }
+
     pub fn reload_data(&mut self) -> Result<(), Error> {
         use std::fs::File;
         use bencode::load;
 
         let mut fp = File::open(&self.path)?;
-        println!("Loading {}", self.path.display());
 
         match load(&mut fp) {
             Ok(v) => self.data = Some(v),
             Err(e) => return Err(Error::InvalidFile(format!("{}", e))),
         }
 
+        self.saved_hash = hash_value(self.data.as_ref().unwrap());
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+
         Ok(())
     }
+
+    /// Record a pre-mutation snapshot, discarding stale redo history and capping the stack at
+    /// `UNDO_LIMIT` entries.
+    fn push_undo(&mut self, snapshot: BencValue) {
+        self.redo_stack.clear();
+        self.undo_stack.push(snapshot);
+
+        if self.undo_stack.len() > UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+    }
 }
 
 impl From<IoError> for Error {
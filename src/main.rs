@@ -1,49 +1,264 @@
-use clap::{App, Arg};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process,
+};
+
+use bencode::Value as BencValue;
 
 mod batch;
+// Standalone bencode parser, unused by the CLI (which runs on the `bencode` crate) —
+// declared here only so its own unit tests compile and run. `benches/parse.rs` includes the
+// file directly.
+mod benc;
+mod codec;
 mod interactive;
 
+use flags::Command;
+use interactive::Format;
+
+mod flags {
+    use std::path::PathBuf;
+
+    use clap::{Parser, Subcommand};
+
+    use crate::interactive::Format;
+
+    /// Bencode editor
+    #[derive(Parser)]
+    #[command(name = "bencedit")]
+    pub struct Bencedit {
+        #[command(subcommand)]
+        pub command: Command,
+    }
+
+    #[derive(Subcommand)]
+    pub enum Command {
+        /// Open a file in the interactive shell.
+        Edit {
+            /// Disable every command that mutates the document.
+            #[arg(long)]
+            read_only: bool,
+            /// Write changes to this path instead of the original file.
+            #[arg(long)]
+            output: Option<PathBuf>,
+            /// Encoding to use when saving: `bencode` (default) or `json`.
+            #[arg(long, value_parser = parse_format)]
+            format: Option<Format>,
+            /// Torrent file to open.
+            file: PathBuf,
+        },
+
+        /// Run a command-script file against each given file instead of opening the
+        /// interactive shell.
+        Batch {
+            /// Command-script file to run against each input file. May be repeated.
+            #[arg(long = "script", required = true)]
+            scripts: Vec<PathBuf>,
+            /// Stop at the first script command that fails instead of continuing and
+            /// reporting every failure at the end.
+            #[arg(long)]
+            stop_on_error: bool,
+            /// Skip invalid files instead of reporting them as failures.
+            #[arg(short = 'S', long)]
+            skip_invalid: bool,
+            /// Skip files that don't exist.
+            #[arg(short = 'N', long)]
+            skip_not_found: bool,
+            /// Process this many files concurrently. Defaults to the number of available CPUs.
+            #[arg(short = 'j', long)]
+            jobs: Option<usize>,
+            /// Descend into subdirectories when an input is a directory, instead of only
+            /// processing its direct children.
+            #[arg(short = 'r', long)]
+            recursive: bool,
+            /// Only process files matching this glob when an input is a directory. Defaults
+            /// to `*.torrent`.
+            #[arg(long)]
+            glob: Option<String>,
+            /// Run every script but write nothing, reporting which files would change.
+            #[arg(long)]
+            check: bool,
+            /// Show a live progress bar. Defaults to on when stderr is a terminal, falling
+            /// back to periodic status lines otherwise.
+            #[arg(long, overrides_with = "no_progress")]
+            progress: bool,
+            /// Disable progress reporting entirely.
+            #[arg(long, overrides_with = "progress")]
+            no_progress: bool,
+            /// Disable every command that mutates the document.
+            #[arg(long)]
+            read_only: bool,
+            /// Write changes to this path instead of the original file.
+            #[arg(long)]
+            output: Option<PathBuf>,
+            /// Encoding to use when saving: `bencode` (default) or `json`.
+            #[arg(long, value_parser = parse_format)]
+            format: Option<Format>,
+            /// Torrent file(s), directories, or glob patterns to process.
+            #[arg(required = true)]
+            file: Vec<PathBuf>,
+        },
+
+        /// Print the value at a bencode key-path to stdout, e.g. `.info.files[0].length`.
+        Get {
+            /// Torrent file to read.
+            file: PathBuf,
+            /// Selector path. Defaults to the document root.
+            #[arg(default_value = "")]
+            path: String,
+        },
+
+        /// Apply a single mutation at a bencode key-path and rewrite the file.
+        Set {
+            /// Torrent file to edit.
+            file: PathBuf,
+            /// Selector path, e.g. `.info.files[0].length`.
+            path: String,
+            /// New value, as JSON.
+            value: String,
+            /// Write changes to this path instead of the original file.
+            #[arg(long)]
+            output: Option<PathBuf>,
+            /// Encoding to use when saving: `bencode` (default) or `json`.
+            #[arg(long, value_parser = parse_format)]
+            format: Option<Format>,
+        },
+
+        /// Check that file(s) parse as valid bencode, printing a line per file and changing
+        /// nothing.
+        Validate {
+            /// Torrent file(s) to check.
+            #[arg(required = true)]
+            file: Vec<PathBuf>,
+        },
+    }
+
+    fn parse_format(s: &str) -> Result<Format, String> {
+        s.parse()
+    }
+}
+
 pub fn main() {
-    let args = App::new("bencedit")
-        .about("Bencode editor")
-        .arg(Arg::with_name("batch")
-             .help("Process several files through transforms")
-             .long("batch")
-             .short("b"))
-        .arg(Arg::with_name("transform")
-             .help("An action to apply to files in batch mode")
-             .requires("batch")
-             .takes_value(true)
-             .number_of_values(1)
-             .multiple(true)
-             .long("transform")
-             .short("t"))
-        .arg(Arg::with_name("skip_invalid")
-             .help("In batch mode, skip invalid files")
-             .requires("batch")
-             .long("skip-invalid")
-             .short("S"))
-        .arg(Arg::with_name("skip_not_found")
-             .help("In batch mode, skip non-existant files")
-             .requires("batch")
-             .long("skip-not-found")
-             .short("N"))
-        .arg(Arg::with_name("files")
-             .multiple(true)
-             .required(true))
-        .get_matches();
-
-    if args.is_present("batch") {
-        if let Err(e) = batch::batch(args.values_of("files").unwrap().collect()) {
-            eprintln!("Error: {}", e);
+    let flags = <flags::Bencedit as clap::Parser>::parse();
+
+    let result = match flags.command {
+        Command::Edit { read_only, output, format, file } => {
+            interactive::interactive(file, read_only, output, format.unwrap_or_default())
+                .map_err(|e| e.to_string())
         }
-    } else {
-        if args.occurrences_of("files") > 1 {
-            println!("Warning: Many files were passed to interactive mode, only the first one will be loaded.")
+
+        Command::Batch {
+            scripts,
+            stop_on_error,
+            skip_invalid,
+            skip_not_found,
+            jobs,
+            recursive,
+            glob,
+            check,
+            progress,
+            no_progress,
+            read_only,
+            output,
+            format,
+            file,
+        } => {
+            let jobs = jobs.unwrap_or_else(|| {
+                std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+            });
+
+            let progress = if progress {
+                Some(true)
+            } else if no_progress {
+                Some(false)
+            } else {
+                None
+            };
+
+            let options = batch::BatchOptions {
+                stop_on_error,
+                read_only,
+                output,
+                format: format.unwrap_or_default(),
+                jobs,
+                skip_invalid,
+                skip_not_found,
+                recursive,
+                glob,
+                check,
+                progress,
+            };
+
+            match batch::batch(file, scripts, options) {
+                Err(e) if e.has_invalid_file() => {
+                    eprintln!("Error: {}", e);
+                    process::exit(2);
+                }
+                result => result.map_err(|e| e.to_string()),
+            }
         }
 
-        if let Err(e) = interactive::interactive(args.value_of("files").unwrap()) {
-            eprintln!("Error: {}", e);
+        Command::Get { file, path } => get(&file, &path),
+        Command::Set { file, path, value, output, format } => {
+            set(&file, &path, &value, output, format.unwrap_or_default())
+        }
+        Command::Validate { file } => validate(&file),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Load `file`, select `path` and print the resulting value to stdout.
+fn get(file: &Path, path: &str) -> Result<(), String> {
+    let data = interactive::load_file(file).map_err(|e| e.to_string())?;
+    let value = data.select(path).map_err(|e| e.to_string())?;
+
+    println!("{}", value);
+    Ok(())
+}
+
+/// Load `file`, replace the value at `path` with `value` (parsed as JSON) and write the
+/// result to `output` (or back to `file` if unset).
+fn set(file: &Path, path: &str, value: &str, output: Option<PathBuf>, format: Format) -> Result<(), String> {
+    use nanoserde::DeJson;
+
+    let mut data = interactive::load_file(file).map_err(|e| e.to_string())?;
+    let target = data.select_mut(path).map_err(|e| e.to_string())?;
+
+    *target = BencValue::deserialize_json(value)
+        .map_err(|e| format!("{}, at {}:{}", e.msg.trim_end(), e.line + 1, e.col))?;
+
+    let out_path = match output {
+        Some(output) => output,
+        None => file.canonicalize().map_err(|e| e.to_string())?,
+    };
+
+    let mut fp = File::create(out_path).map_err(|e| e.to_string())?;
+    interactive::write_formatted(&data, format, &mut fp).map_err(|e| e.to_string())
+}
+
+/// Check that every file in `files` parses as valid bencode, printing a line per file and
+/// failing if any of them didn't.
+fn validate(files: &[PathBuf]) -> Result<(), String> {
+    let mut failed = false;
+
+    for file in files {
+        match interactive::load_file(file) {
+            Ok(_) => println!("{}: OK", file.display()),
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                failed = true;
+            }
         }
     }
+
+    if failed {
+        Err("one or more files failed validation".to_string())
+    } else {
+        Ok(())
+    }
 }